@@ -1,5 +1,6 @@
 use core::array::TryFromSliceError;
 
+use alloc::string::String;
 use alloc::vec::Vec;
 
 use crate::file_header::FileChecksum;
@@ -12,6 +13,41 @@ pub enum BlockDeserialiserError {
 	DataLengthMissMatch,
 	TryFromSliceError,
 	EncodingError(u16),
+	DecompressionError,
+	HeatshrinkDecodeError,
+	ChecksumMismatch { expected: u32, found: u32 },
+	MeatPackDecodeError,
+}
+
+/// The image format of a [`BlockKind::Thumbnail`] block's payload.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ThumbnailEncoding {
+	Png,
+	Jpg,
+	Qoi,
+}
+
+impl ThumbnailEncoding {
+	pub fn new(value: u16) -> Result<Self, BlockDeserialiserError> {
+		match value {
+			0 => Ok(Self::Png),
+			1 => Ok(Self::Jpg),
+			2 => Ok(Self::Qoi),
+			v => Err(BlockDeserialiserError::EncodingError(v)),
+		}
+	}
+}
+
+/// The decoded contents of a block, in the richer form its `encoding`
+/// field implies rather than as raw bytes.
+#[derive(Debug)]
+pub enum DeserialisedBlockData {
+	Ini(Vec<u8>),
+	GCode(String),
+	Thumbnail {
+		encoding: ThumbnailEncoding,
+		data: Vec<u8>,
+	},
 }
 
 #[derive(Debug)]
@@ -40,11 +76,11 @@ impl BlockKind {
 	pub fn to_le_bytes(&self) -> [u8; 2] {
 		match *self {
 			BlockKind::FileMetadata => 0u16.to_le_bytes(),
-			BlockKind::GCode => 1u16.to_be_bytes(),
-			BlockKind::SlicerMetadata => 1u16.to_le_bytes(),
-			BlockKind::PrinterMetadata => 2u16.to_le_bytes(),
-			BlockKind::PrintMetadata => 3u16.to_le_bytes(),
-			BlockKind::Thumbnail => 4u16.to_le_bytes(),
+			BlockKind::GCode => 1u16.to_le_bytes(),
+			BlockKind::SlicerMetadata => 2u16.to_le_bytes(),
+			BlockKind::PrinterMetadata => 3u16.to_le_bytes(),
+			BlockKind::PrintMetadata => 4u16.to_le_bytes(),
+			BlockKind::Thumbnail => 5u16.to_le_bytes(),
 		}
 	}
 
@@ -85,7 +121,7 @@ impl CompressionAlgorithm {
 	pub fn to_le_bytes(&self) -> [u8; 2] {
 		match *self {
 			CompressionAlgorithm::None => 0u16.to_le_bytes(),
-			CompressionAlgorithm::Deflate => 1u16.to_be_bytes(),
+			CompressionAlgorithm::Deflate => 1u16.to_le_bytes(),
 			CompressionAlgorithm::Heatshrink11_4 => 2u16.to_le_bytes(),
 			CompressionAlgorithm::Heatshrink12_4 => 3u16.to_le_bytes(),
 		}
@@ -109,6 +145,10 @@ fn try_from_slice<const N: usize>(buf: &[u8]) -> Result<[u8; N], BlockDeserialis
 pub struct BlockDeserialiser {
 	buf: Vec<u8>,
 	checksum: FileChecksum,
+	/// When `false`, a stored CRC-32 is parsed but not verified. Useful for
+	/// reading files that declare a checksum but were produced by a writer
+	/// known to get it wrong.
+	verify_checksum: bool,
 }
 
 impl BlockDeserialiser {
@@ -116,7 +156,31 @@ impl BlockDeserialiser {
 		Self {
 			buf: Vec::with_capacity(12),
 			checksum,
+			verify_checksum: true,
+		}
+	}
+
+	/// Like [`BlockDeserialiser::new`], but skips CRC-32 verification even
+	/// when `checksum` is [`FileChecksum::Crc32`].
+	pub fn new_lenient(checksum: FileChecksum) -> Self {
+		Self {
+			buf: Vec::with_capacity(12),
+			checksum,
+			verify_checksum: false,
+		}
+	}
+
+	fn verify_crc32(&self, end: usize) -> Result<(), BlockDeserialiserError> {
+		if !self.verify_checksum {
+			return Ok(());
+		}
+		let stored = try_from_slice::<4>(&self.buf[end..end + 4])?;
+		let expected = u32::from_le_bytes(stored);
+		let found = crate::crc32::checksum(&self.buf[0..end]);
+		if expected != found {
+			return Err(BlockDeserialiserError::ChecksumMismatch { expected, found });
 		}
+		Ok(())
 	}
 
 	pub fn kind(&self) -> Result<BlockKind, BlockDeserialiserError> {
@@ -146,18 +210,26 @@ impl BlockDeserialiser {
 	}
 
 	pub fn block_size(&self) -> Result<usize, BlockDeserialiserError> {
+		// `header_buf` always reads a fixed 12-byte prefix, but an
+		// uncompressed block's on-disk header is only 8 bytes (it has no
+		// `compressed_size` field), so 4 of those already-consumed bytes
+		// actually belong to the parameters/payload computed below.
+		let header_overread = match self.compression()? {
+			CompressionAlgorithm::None => 4,
+			_ => 0,
+		};
+
 		let mut size: usize = 0;
 		size += self.kind()?.parameter_byte_size();
 		size += self.checksum.checksum_byte_size();
-		let c = self.compression()?;
-		match c {
-			CompressionAlgorithm::None => {
-				size -= 4; // less four bytes as we have already have and the header is actually [u8; 8].
-				size += self.uncompressed_size()?;
-			}
-			_ => size += self.compressed_size()?,
-		}
-		Ok(size)
+		size += match self.compression()? {
+			CompressionAlgorithm::None => self.uncompressed_size()?,
+			_ => self.compressed_size()?,
+		};
+
+		size
+			.checked_sub(header_overread)
+			.ok_or(BlockDeserialiserError::DataLengthMissMatch)
 	}
 
 	pub fn header_buf(&mut self) -> &mut [u8] {
@@ -178,7 +250,7 @@ impl BlockDeserialiser {
 		Ok(slice)
 	}
 
-	pub fn deserialise(&self) -> Result<Vec<u8>, BlockDeserialiserError> {
+	pub fn deserialise(&self) -> Result<DeserialisedBlockData, BlockDeserialiserError> {
 		// Check the expected and received lengths
 		// The user may have forgetton to read in the data
 		let buf_length_check = 12 + self.block_size()?;
@@ -187,77 +259,68 @@ impl BlockDeserialiser {
 		}
 
 		match self.kind()? {
-			BlockKind::FileMetadata => self.deserialise_ini_data(),
-			BlockKind::GCode => todo!(),
-			BlockKind::PrintMetadata => self.deserialise_ini_data(),
-			BlockKind::PrinterMetadata => self.deserialise_ini_data(),
-			BlockKind::SlicerMetadata => self.deserialise_ini_data(),
+			BlockKind::FileMetadata => Ok(DeserialisedBlockData::Ini(self.deserialise_ini_data()?)),
+			BlockKind::GCode => self.deserialise_gcode_data(),
+			BlockKind::PrintMetadata => Ok(DeserialisedBlockData::Ini(self.deserialise_ini_data()?)),
+			BlockKind::PrinterMetadata => Ok(DeserialisedBlockData::Ini(self.deserialise_ini_data()?)),
+			BlockKind::SlicerMetadata => Ok(DeserialisedBlockData::Ini(self.deserialise_ini_data()?)),
 			BlockKind::Thumbnail => self.deserialise_thumbnail_data(),
 		}
 	}
 
-	fn deserialise_thumbnail_data(&self) -> Result<Vec<u8>, BlockDeserialiserError> {
-		let data: Vec<u8> = Vec::new();
-		let c = self.compression()?;
-		let mut idx: usize;
-		match c {
-			CompressionAlgorithm::None => idx = 8,
-			_ => idx = 12,
-		}
-		let encoding = try_from_slice::<2>(&self.buf[idx..=idx + 1])?;
+	/// Returns the start of this block's payload and where it ends
+	/// (before any trailing checksum), verifying the checksum along the
+	/// way. Also returns the raw `encoding` parameter word: for most block
+	/// kinds this is the only parameter word, but [`BlockKind::Thumbnail`]
+	/// has a 6-byte `[width, height, encoding]` parameter block, so
+	/// `encoding` there is the third word, not the first.
+	fn payload_bounds(&self) -> Result<(u16, usize, usize), BlockDeserialiserError> {
+		let idx = match self.compression()? {
+			CompressionAlgorithm::None => 8,
+			_ => 12,
+		};
+		let parameter_byte_size = self.kind()?.parameter_byte_size();
+		let encoding_idx = idx + parameter_byte_size - 2;
+		let encoding = try_from_slice::<2>(&self.buf[encoding_idx..=encoding_idx + 1])?;
 		let encoding = u16::from_le_bytes(encoding);
-		if encoding > 2 {
-			return Err(BlockDeserialiserError::EncodingError(encoding));
-		}
-		// Start of the data
-		let start = idx + 2;
-		let mut end: usize;
-		match self.checksum {
-			FileChecksum::None => end = self.buf.len(),
+
+		let start = idx + parameter_byte_size;
+		let end = match self.checksum {
+			FileChecksum::None => self.buf.len(),
 			FileChecksum::Crc32 => {
-				end = self.buf.len() - 4;
-				let checksum = &self.buf[end..];
-				// TODO: deal with the checksum
+				let end = self.buf.len() - 4;
+				self.verify_crc32(end)?;
+				end
 			}
-		}
+		};
 
-		// Deal with the data
-		let data = self.deserialise_data(start, end)?;
+		Ok((encoding, start, end))
+	}
 
-		// Then the encoding (if required)
-		Ok(data)
+	fn deserialise_thumbnail_data(&self) -> Result<DeserialisedBlockData, BlockDeserialiserError> {
+		let (encoding, start, end) = self.payload_bounds()?;
+		let encoding = ThumbnailEncoding::new(encoding)?;
+		let data = self.deserialise_data(start, end)?;
+		Ok(DeserialisedBlockData::Thumbnail { encoding, data })
 	}
 
 	fn deserialise_ini_data(&self) -> Result<Vec<u8>, BlockDeserialiserError> {
-		let data: Vec<u8> = Vec::new();
-		let c = self.compression()?;
-		let mut idx: usize;
-		match c {
-			CompressionAlgorithm::None => idx = 8,
-			_ => idx = 12,
-		}
-		let encoding = try_from_slice::<2>(&self.buf[idx..=idx + 1])?;
-		let encoding = u16::from_le_bytes(encoding);
+		let (encoding, start, end) = self.payload_bounds()?;
 		if encoding != 0 {
 			return Err(BlockDeserialiserError::EncodingError(encoding));
 		}
-		// Start of the data
-		let start = idx + 2;
-		let mut end: usize;
-		match self.checksum {
-			FileChecksum::None => end = self.buf.len(),
-			FileChecksum::Crc32 => {
-				end = self.buf.len() - 4;
-				let checksum = &self.buf[end..];
-				// TODO: deal with the checksum
-			}
-		}
+		self.deserialise_data(start, end)
+	}
 
-		// Deal with the data
+	fn deserialise_gcode_data(&self) -> Result<DeserialisedBlockData, BlockDeserialiserError> {
+		let (encoding, start, end) = self.payload_bounds()?;
 		let data = self.deserialise_data(start, end)?;
-
-		// Then the encoding (if required)
-		Ok(data)
+		let gcode = match encoding {
+			0 => String::from_utf8(data).map_err(|_| BlockDeserialiserError::MeatPackDecodeError)?,
+			1 | 2 => crate::meatpack::decode(&data)?,
+			v => return Err(BlockDeserialiserError::EncodingError(v)),
+		};
+		Ok(DeserialisedBlockData::GCode(gcode))
 	}
 
 	fn deserialise_data(
@@ -265,179 +328,109 @@ impl BlockDeserialiser {
 		start: usize,
 		end: usize,
 	) -> Result<Vec<u8>, BlockDeserialiserError> {
-		let mut data: Vec<u8> = Vec::new();
-		match self.compression()? {
-			CompressionAlgorithm::None => {
-				for v in self.buf[start..end].iter() {
-					data.push(*v);
-				}
-			}
-			CompressionAlgorithm::Deflate => {
-				todo!()
-			}
-			CompressionAlgorithm::Heatshrink11_4 => {
-				todo!()
-			}
-			CompressionAlgorithm::Heatshrink12_4 => {
-				todo!()
-			}
-		}
+		let codec = crate::codec::codec_for(&self.compression()?);
+		let data = codec.decode(&self.buf[start..end], self.uncompressed_size()?)?;
 		Ok(data)
 	}
 }
 
-/*
-#[derive(Debug)]
-pub struct Block {
-	pub kind: BlockKind,
-	pub compression: CompressionAlgorithm,
-	pub uncompressed_size: u32,
-	pub compressed_size: Option<u32>,
-	pub parameters: Option<[u16; 3]>,
-	pub crc: Option<u32>,
-}
-
-impl Block {
-	pub fn new(
-		kind: BlockKind,
-		compression: CompressionAlgorithm,
-		uncompressed_size: u32,
-		compressed_size: Option<u32>,
-		parameters: Option<[u16; 3]>,
-		crc: Option<u32>,
-	) -> Self {
-		Self {
-			kind,
-			compression,
-			compressed_size,
-			uncompressed_size,
-			parameters,
-			crc,
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::serialiser::BlockSerialiser;
+	use alloc::vec;
+
+	#[test]
+	fn block_size_handles_uncompressed_block_with_no_checksum() {
+		// The most common real-world combination: an uncompressed block in
+		// a file with no trailing CRC-32. `block_size` must not underflow
+		// just because there's no checksum to add back in.
+		let payload = b"; generated by a test\nG28\n".to_vec();
+		let serialised = BlockSerialiser::new(
+			BlockKind::PrintMetadata,
+			CompressionAlgorithm::None,
+			vec![0],
+			payload.clone(),
+			FileChecksum::None,
+		)
+		.serialise()
+		.unwrap();
+
+		let mut deserialiser = BlockDeserialiser::new(FileChecksum::None);
+		deserialiser
+			.header_buf()
+			.copy_from_slice(&serialised[0..12]);
+		deserialiser
+			.data_buf()
+			.unwrap()
+			.copy_from_slice(&serialised[12..]);
+
+		match deserialiser.deserialise().unwrap() {
+			DeserialisedBlockData::Ini(data) => assert_eq!(data, payload),
+			other => panic!("expected Ini data, got {other:?}"),
 		}
 	}
 
-	pub fn read_header(bytes: &[u8; 12]) -> Result<Block, BlockError> {
-		let b_bytes: [u8; 2] = bytes[0..=1].try_into().unwrap();
-		let kind = BlockKind::from_le_bytes(b_bytes)?;
-
-		let c_bytes = bytes[2..=3].try_into().unwrap();
-		let compression = CompressionAlgorithm::from_le_bytes(c_bytes)?;
-
-		let uncompressed_size: [u8; 4] = bytes[4..=7].try_into().unwrap();
-		let uncompressed_size = u32::from_le_bytes(uncompressed_size);
-
-		match compression {
-			CompressionAlgorithm::None => Ok(Self {
-				kind,
-				compression,
-				uncompressed_size,
-				compressed_size: None,
-				parameters: None,
-				crc: None,
-			}),
-			_ => {
-				let compressed_size: [u8; 4] = bytes[8..=11].try_into().unwrap();
-				let compressed_size = u32::from_le_bytes(compressed_size);
-				Ok(Self {
-					kind,
-					compression,
-					uncompressed_size,
-					compressed_size: Some(compressed_size),
-					parameters: None,
-					crc: None,
-				})
-			}
+	#[test]
+	fn detects_checksum_mismatch() {
+		let payload = b"; generated by a test\nG28\n".to_vec();
+		let mut serialised = BlockSerialiser::new(
+			BlockKind::PrintMetadata,
+			CompressionAlgorithm::None,
+			vec![0],
+			payload,
+			FileChecksum::Crc32,
+		)
+		.serialise()
+		.unwrap();
+
+		// Corrupt a payload byte without touching the trailing CRC-32.
+		let corrupt_at = serialised.len() - 5;
+		serialised[corrupt_at] ^= 0xFF;
+
+		let mut deserialiser = BlockDeserialiser::new(FileChecksum::Crc32);
+		deserialiser
+			.header_buf()
+			.copy_from_slice(&serialised[0..12]);
+		deserialiser
+			.data_buf()
+			.unwrap()
+			.copy_from_slice(&serialised[12..]);
+
+		match deserialiser.deserialise() {
+			Err(BlockDeserialiserError::ChecksumMismatch { .. }) => (),
+			other => panic!("expected ChecksumMismatch, got {other:?}"),
 		}
 	}
 
-	// Note. checks for negative values (which we should not get).
-	pub fn block_size(
-		&self,
-		checksum: &FileChecksum,
-	) -> usize {
-		let mut size: usize = 0;
-		size += self.kind.parameter_byte_size();
-		size += checksum.checksum_byte_size();
-		if let Some(c) = self.compressed_size {
-			size += c as usize;
-		} else {
-			size += self.uncompressed_size as usize;
-		}
-		size
-	}
-
-	pub fn create_block_data_buffer(
-		&self,
-		checksum: &FileChecksum,
-	) -> Vec<u8> {
-		Vec::with_capacity(self.block_size(checksum))
-	}
-
-	pub fn deserialise_block_data(
-		&mut self,
-		data: &[u8],
-		checksum: &FileChecksum,
-	) -> Result<Vec<u8>, BlockError> {
-		if data.len() != self.block_size(checksum) {
-			return Err(BlockError::DataLengthMissMatch);
-		}
-
-		// Parameter data
-		let mut parameter_data: [u16; 3] = [0; 3];
-		let mut start: usize = 0;
-		match self.kind {
-			BlockKind::Thumbnail => {
-				for (i, j) in [0, 2, 4].iter().enumerate() {
-					let p = data[*j..=*j + 1].try_into().unwrap();
-					let p = u16::from_le_bytes(p);
-					parameter_data[i] = p;
-				}
-				start += 6;
-			}
-			_ => {
-				let p = data[0..=1].try_into().unwrap();
-				let p = u16::from_le_bytes(p);
-				parameter_data[0] = p;
-				start += 2;
+	#[test]
+	fn deserialises_thumbnail_with_non_trivial_dimensions() {
+		let payload = vec![0x89, b'P', b'N', b'G', 1, 2, 3, 4];
+		let serialised = BlockSerialiser::new(
+			BlockKind::Thumbnail,
+			CompressionAlgorithm::None,
+			vec![200, 150, 0], // width, height, encoding=Png
+			payload.clone(),
+			FileChecksum::Crc32,
+		)
+		.serialise()
+		.unwrap();
+
+		let mut deserialiser = BlockDeserialiser::new(FileChecksum::Crc32);
+		deserialiser
+			.header_buf()
+			.copy_from_slice(&serialised[0..12]);
+		deserialiser
+			.data_buf()
+			.unwrap()
+			.copy_from_slice(&serialised[12..]);
+
+		match deserialiser.deserialise().unwrap() {
+			DeserialisedBlockData::Thumbnail { encoding, data } => {
+				assert_eq!(encoding, ThumbnailEncoding::Png);
+				assert_eq!(data, payload);
 			}
+			other => panic!("expected Thumbnail data, got {other:?}"),
 		}
-		self.parameters = Some(parameter_data);
-
-		// CRC
-		let mut end = data.len();
-		let mut crc: Option<u32> = None;
-		match checksum {
-			FileChecksum::None => {}
-			FileChecksum::Crc32 => {
-				end -= 4;
-				let c: [u8; 4] = data[data.len() - 4..data.len()].try_into().unwrap();
-				crc = Some(u32::from_le_bytes(c))
-			}
-		}
-		self.crc = crc;
-
-		// TODO: pass the data and perform the crc check.
-		// Assume the CRC check is on the data received and not
-		// the uncompress version.
-
-		let data = &data[start..end];
-
-		// Initialise the vector to parse the data into
-		let len = self.uncompressed_size as usize;
-		let mut v = Vec::with_capacity(len);
-		for i in 0..len {
-			v.push(0);
-		}
-
-		// Decompress the data
-		match self.compression {
-			CompressionAlgorithm::None => v = data.to_vec(),
-			_ => todo!(),
-		}
-
-		// Checking for any encoding that also needs
-		// to be sorted.
-		Ok(v)
 	}
 }
-*/
@@ -0,0 +1,121 @@
+//! Pluggable compression backends for block payloads.
+//!
+//! [`BlockDeserialiser`](crate::block::BlockDeserialiser) and
+//! [`BlockSerialiser`](crate::serialiser::BlockSerialiser) no longer hard
+//! code a `match` over [`CompressionAlgorithm`](crate::block::CompressionAlgorithm);
+//! instead they go through a [`Codec`] trait object picked by [`codec_for`].
+//! `codec_for` only knows the four built-in algorithms, and neither block
+//! (de)serialiser accepts any other codec, so a `Codec` implemented outside
+//! this crate is not used by them.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::block::BlockDeserialiserError;
+use crate::deflate;
+use crate::heatshrink;
+
+/// A compression backend for block payloads.
+pub trait Codec {
+	/// Decode `input` into exactly `expected_len` bytes.
+	fn decode(&self, input: &[u8], expected_len: usize) -> Result<Vec<u8>, BlockDeserialiserError>;
+
+	/// Encode `input` into this codec's on-disk representation.
+	fn encode(&self, input: &[u8]) -> Result<Vec<u8>, BlockDeserialiserError>;
+}
+
+/// The identity codec, used for uncompressed blocks.
+pub struct NoneCodec;
+
+impl Codec for NoneCodec {
+	fn decode(&self, input: &[u8], expected_len: usize) -> Result<Vec<u8>, BlockDeserialiserError> {
+		if input.len() != expected_len {
+			return Err(BlockDeserialiserError::DecompressionError);
+		}
+		Ok(input.to_vec())
+	}
+
+	fn encode(&self, input: &[u8]) -> Result<Vec<u8>, BlockDeserialiserError> {
+		Ok(input.to_vec())
+	}
+}
+
+/// ZLIB-wrapped DEFLATE, as used by [`CompressionAlgorithm::Deflate`](crate::block::CompressionAlgorithm::Deflate).
+pub struct DeflateCodec;
+
+impl Codec for DeflateCodec {
+	fn decode(&self, input: &[u8], expected_len: usize) -> Result<Vec<u8>, BlockDeserialiserError> {
+		deflate::decode(input, expected_len)
+	}
+
+	fn encode(&self, input: &[u8]) -> Result<Vec<u8>, BlockDeserialiserError> {
+		deflate::encode(input)
+	}
+}
+
+/// Heatshrink with a fixed window/lookahead size, as used by
+/// [`CompressionAlgorithm::Heatshrink11_4`](crate::block::CompressionAlgorithm::Heatshrink11_4) and
+/// [`CompressionAlgorithm::Heatshrink12_4`](crate::block::CompressionAlgorithm::Heatshrink12_4).
+pub struct HeatshrinkCodec {
+	pub window_bits: u8,
+	pub lookahead_bits: u8,
+}
+
+impl Codec for HeatshrinkCodec {
+	fn decode(&self, input: &[u8], expected_len: usize) -> Result<Vec<u8>, BlockDeserialiserError> {
+		heatshrink::decode(input, self.window_bits, self.lookahead_bits, expected_len)
+	}
+
+	fn encode(&self, input: &[u8]) -> Result<Vec<u8>, BlockDeserialiserError> {
+		heatshrink::encode(input)
+	}
+}
+
+/// Boxes up the built-in [`Codec`] for a given algorithm. This is the only
+/// codec lookup the block (de)serialisers use; there is no way to hand them
+/// a different `Box<dyn Codec>`.
+pub(crate) fn codec_for(algorithm: &crate::block::CompressionAlgorithm) -> Box<dyn Codec> {
+	use crate::block::CompressionAlgorithm;
+	match algorithm {
+		CompressionAlgorithm::None => Box::new(NoneCodec),
+		CompressionAlgorithm::Deflate => Box::new(DeflateCodec),
+		CompressionAlgorithm::Heatshrink11_4 => Box::new(HeatshrinkCodec {
+			window_bits: 11,
+			lookahead_bits: 4,
+		}),
+		CompressionAlgorithm::Heatshrink12_4 => Box::new(HeatshrinkCodec {
+			window_bits: 12,
+			lookahead_bits: 4,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn none_codec_round_trips() {
+		let codec = NoneCodec;
+		let encoded = codec.encode(b"hello").unwrap();
+		let decoded = codec.decode(&encoded, 5).unwrap();
+		assert_eq!(decoded, b"hello");
+	}
+
+	#[test]
+	fn none_codec_rejects_length_mismatch() {
+		let codec = NoneCodec;
+		assert!(codec.decode(b"hello", 4).is_err());
+	}
+
+	#[test]
+	fn heatshrink_codec_round_trips() {
+		let codec = HeatshrinkCodec {
+			window_bits: 11,
+			lookahead_bits: 4,
+		};
+		let encoded = codec.encode(b"hello").unwrap();
+		let decoded = codec.decode(&encoded, 5).unwrap();
+		assert_eq!(decoded, b"hello");
+	}
+}
@@ -0,0 +1,36 @@
+//! CRC-32 (IEEE 802.3), as used for the optional per-block checksum.
+//!
+//! Reflected polynomial 0xEDB88320, initial value 0xFFFFFFFF, final value
+//! XORed with 0xFFFFFFFF.
+
+const fn build_table() -> [u32; 256] {
+	let mut table = [0u32; 256];
+	let mut i = 0;
+	while i < 256 {
+		let mut crc = i as u32;
+		let mut bit = 0;
+		while bit < 8 {
+			crc = if crc & 1 != 0 {
+				(crc >> 1) ^ 0xEDB8_8320
+			} else {
+				crc >> 1
+			};
+			bit += 1;
+		}
+		table[i] = crc;
+		i += 1;
+	}
+	table
+}
+
+const TABLE: [u32; 256] = build_table();
+
+/// Compute the CRC-32 of `data`.
+pub(crate) fn checksum(data: &[u8]) -> u32 {
+	let mut crc: u32 = 0xFFFF_FFFF;
+	for &byte in data {
+		let idx = ((crc ^ byte as u32) & 0xFF) as usize;
+		crc = (crc >> 8) ^ TABLE[idx];
+	}
+	crc ^ 0xFFFF_FFFF
+}
@@ -0,0 +1,447 @@
+//! Minimal ZLIB/DEFLATE decoder (RFC 1950 / RFC 1951).
+//!
+//! Block payloads tagged with `CompressionAlgorithm::Deflate` are a ZLIB
+//! stream: a 2-byte CMF/FLG header, a raw DEFLATE body (fixed and dynamic
+//! Huffman blocks with LZ77 back-references into the already-produced
+//! output), and a trailing big-endian Adler-32 checksum of the decoded
+//! bytes.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::block::BlockDeserialiserError;
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+	3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+	163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+	0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+	1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+	2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA_BITS: [u8; 30] = [
+	0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+	13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+	16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+	data: &'a [u8],
+	byte_pos: usize,
+	bit_pos: u8,
+}
+
+impl<'a> BitReader<'a> {
+	fn new(data: &'a [u8]) -> Self {
+		Self {
+			data,
+			byte_pos: 0,
+			bit_pos: 0,
+		}
+	}
+
+	fn read_bit(&mut self) -> Result<u32, BlockDeserialiserError> {
+		let byte = *self
+			.data
+			.get(self.byte_pos)
+			.ok_or(BlockDeserialiserError::DecompressionError)?;
+		let bit = (byte >> self.bit_pos) & 1;
+		self.bit_pos += 1;
+		if self.bit_pos == 8 {
+			self.bit_pos = 0;
+			self.byte_pos += 1;
+		}
+		Ok(bit as u32)
+	}
+
+	fn read_bits(&mut self, count: u8) -> Result<u32, BlockDeserialiserError> {
+		let mut value = 0u32;
+		for i in 0..count {
+			value |= self.read_bit()? << i;
+		}
+		Ok(value)
+	}
+
+	fn align_to_byte(&mut self) {
+		if self.bit_pos != 0 {
+			self.bit_pos = 0;
+			self.byte_pos += 1;
+		}
+	}
+
+	fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], BlockDeserialiserError> {
+		let end = self
+			.byte_pos
+			.checked_add(count)
+			.ok_or(BlockDeserialiserError::DecompressionError)?;
+		let slice = self
+			.data
+			.get(self.byte_pos..end)
+			.ok_or(BlockDeserialiserError::DecompressionError)?;
+		self.byte_pos = end;
+		Ok(slice)
+	}
+}
+
+/// A canonical Huffman code table, built from a list of per-symbol code
+/// lengths following the algorithm in RFC 1951 section 3.2.2.
+struct Huffman {
+	counts: [u16; MAX_BITS + 1],
+	symbols: Vec<u16>,
+}
+
+impl Huffman {
+	fn construct(lengths: &[u8]) -> Self {
+		let mut counts = [0u16; MAX_BITS + 1];
+		for &len in lengths {
+			counts[len as usize] += 1;
+		}
+		counts[0] = 0;
+
+		let mut offsets = [0u16; MAX_BITS + 1];
+		for len in 1..=MAX_BITS {
+			offsets[len] = offsets[len - 1] + counts[len - 1];
+		}
+
+		let mut symbols = vec![0u16; lengths.len()];
+		for (symbol, &len) in lengths.iter().enumerate() {
+			if len != 0 {
+				symbols[offsets[len as usize] as usize] = symbol as u16;
+				offsets[len as usize] += 1;
+			}
+		}
+
+		Self { counts, symbols }
+	}
+
+	fn decode(&self, reader: &mut BitReader) -> Result<u16, BlockDeserialiserError> {
+		let mut code: i32 = 0;
+		let mut first: i32 = 0;
+		let mut index: i32 = 0;
+		for len in 1..=MAX_BITS {
+			code |= reader.read_bit()? as i32;
+			let count = self.counts[len] as i32;
+			if code - first < count {
+				return Ok(self.symbols[(index + (code - first)) as usize]);
+			}
+			index += count;
+			first += count;
+			first <<= 1;
+			code <<= 1;
+		}
+		Err(BlockDeserialiserError::DecompressionError)
+	}
+}
+
+fn fixed_huffman() -> (Huffman, Huffman) {
+	let mut lit_lengths = [0u8; 288];
+	for (i, l) in lit_lengths.iter_mut().enumerate() {
+		*l = match i {
+			0..=143 => 8,
+			144..=255 => 9,
+			256..=279 => 7,
+			_ => 8,
+		};
+	}
+	let dist_lengths = [5u8; 30];
+	(
+		Huffman::construct(&lit_lengths),
+		Huffman::construct(&dist_lengths),
+	)
+}
+
+fn dynamic_huffman(reader: &mut BitReader) -> Result<(Huffman, Huffman), BlockDeserialiserError> {
+	let hlit = reader.read_bits(5)? as usize + 257;
+	let hdist = reader.read_bits(5)? as usize + 1;
+	let hclen = reader.read_bits(4)? as usize + 4;
+
+	let mut code_length_lengths = [0u8; 19];
+	for &order in CODE_LENGTH_ORDER.iter().take(hclen) {
+		code_length_lengths[order] = reader.read_bits(3)? as u8;
+	}
+	let code_length_huffman = Huffman::construct(&code_length_lengths);
+
+	let mut lengths: Vec<u8> = Vec::with_capacity(hlit + hdist);
+	while lengths.len() < hlit + hdist {
+		let symbol = code_length_huffman.decode(reader)?;
+		match symbol {
+			0..=15 => lengths.push(symbol as u8),
+			16 => {
+				let prev = *lengths
+					.last()
+					.ok_or(BlockDeserialiserError::DecompressionError)?;
+				let repeat = reader.read_bits(2)? + 3;
+				for _ in 0..repeat {
+					lengths.push(prev);
+				}
+			}
+			17 => {
+				let repeat = reader.read_bits(3)? + 3;
+				lengths.extend(core::iter::repeat_n(0u8, repeat as usize));
+			}
+			18 => {
+				let repeat = reader.read_bits(7)? + 11;
+				lengths.extend(core::iter::repeat_n(0u8, repeat as usize));
+			}
+			_ => return Err(BlockDeserialiserError::DecompressionError),
+		}
+	}
+	if lengths.len() != hlit + hdist {
+		return Err(BlockDeserialiserError::DecompressionError);
+	}
+
+	let lit_huffman = Huffman::construct(&lengths[..hlit]);
+	let dist_huffman = Huffman::construct(&lengths[hlit..]);
+	Ok((lit_huffman, dist_huffman))
+}
+
+fn inflate_block(
+	reader: &mut BitReader,
+	lit: &Huffman,
+	dist: &Huffman,
+	out: &mut Vec<u8>,
+) -> Result<(), BlockDeserialiserError> {
+	loop {
+		let symbol = lit.decode(reader)?;
+		match symbol {
+			0..=255 => out.push(symbol as u8),
+			256 => return Ok(()),
+			257..=285 => {
+				let idx = (symbol - 257) as usize;
+				let length = LENGTH_BASE[idx] as usize
+					+ reader.read_bits(LENGTH_EXTRA_BITS[idx])? as usize;
+				let dist_symbol = dist.decode(reader)? as usize;
+				let distance = *DIST_BASE
+					.get(dist_symbol)
+					.ok_or(BlockDeserialiserError::DecompressionError)?
+					as usize
+					+ reader.read_bits(
+						*DIST_EXTRA_BITS
+							.get(dist_symbol)
+							.ok_or(BlockDeserialiserError::DecompressionError)?,
+					)? as usize;
+				if distance > out.len() {
+					return Err(BlockDeserialiserError::DecompressionError);
+				}
+				// `out` grows on every iteration and a back-reference may
+				// overlap itself (distance < length), so this can't be
+				// expressed as a range copy over a fixed slice.
+				let mut start = out.len() - distance;
+				#[allow(clippy::explicit_counter_loop)]
+				for _ in 0..length {
+					let byte = out[start];
+					out.push(byte);
+					start += 1;
+				}
+			}
+			_ => return Err(BlockDeserialiserError::DecompressionError),
+		}
+	}
+}
+
+fn inflate(data: &[u8], expected_len: usize) -> Result<Vec<u8>, BlockDeserialiserError> {
+	let mut reader = BitReader::new(data);
+	let mut out = Vec::with_capacity(expected_len);
+
+	loop {
+		let is_final = reader.read_bit()? == 1;
+		let block_type = reader.read_bits(2)?;
+		match block_type {
+			0 => {
+				reader.align_to_byte();
+				let len_bytes = reader.read_bytes(2)?;
+				let len = u16::from_le_bytes([len_bytes[0], len_bytes[1]]) as usize;
+				let nlen_bytes = reader.read_bytes(2)?;
+				let nlen = u16::from_le_bytes([nlen_bytes[0], nlen_bytes[1]]);
+				if nlen != !(len as u16) {
+					return Err(BlockDeserialiserError::DecompressionError);
+				}
+				out.extend_from_slice(reader.read_bytes(len)?);
+			}
+			1 => {
+				let (lit, dist) = fixed_huffman();
+				inflate_block(&mut reader, &lit, &dist, &mut out)?;
+			}
+			2 => {
+				let (lit, dist) = dynamic_huffman(&mut reader)?;
+				inflate_block(&mut reader, &lit, &dist, &mut out)?;
+			}
+			_ => return Err(BlockDeserialiserError::DecompressionError),
+		}
+		if is_final {
+			break;
+		}
+	}
+
+	if out.len() != expected_len {
+		return Err(BlockDeserialiserError::DecompressionError);
+	}
+	Ok(out)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+	const MODULO: u32 = 65521;
+	let mut a: u32 = 1;
+	let mut b: u32 = 0;
+	for &byte in data {
+		a = (a + byte as u32) % MODULO;
+		b = (b + a) % MODULO;
+	}
+	(b << 16) | a
+}
+
+/// Decode a ZLIB-wrapped DEFLATE stream, validating the header and the
+/// trailing Adler-32 checksum, and returning an error if the decoded
+/// length does not match `expected_len`.
+pub(crate) fn decode(input: &[u8], expected_len: usize) -> Result<Vec<u8>, BlockDeserialiserError> {
+	if input.len() < 6 {
+		return Err(BlockDeserialiserError::DecompressionError);
+	}
+	let cmf = input[0];
+	let flg = input[1];
+	if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+		return Err(BlockDeserialiserError::DecompressionError);
+	}
+	if cmf & 0x0F != 8 {
+		// Only the "deflate" compression method is defined by ZLIB.
+		return Err(BlockDeserialiserError::DecompressionError);
+	}
+	if flg & 0x20 != 0 {
+		// FDICT: a preset dictionary is not supported.
+		return Err(BlockDeserialiserError::DecompressionError);
+	}
+
+	let body = &input[2..input.len() - 4];
+	let adler_bytes = &input[input.len() - 4..];
+	let decoded = inflate(body, expected_len)?;
+
+	let expected_adler = u32::from_be_bytes(
+		adler_bytes
+			.try_into()
+			.map_err(|_| BlockDeserialiserError::DecompressionError)?,
+	);
+	if adler32(&decoded) != expected_adler {
+		return Err(BlockDeserialiserError::DecompressionError);
+	}
+
+	Ok(decoded)
+}
+
+/// Encode `input` as a ZLIB-wrapped DEFLATE stream.
+///
+/// This writer emits stored (uncompressed) DEFLATE blocks rather than
+/// Huffman-coded ones: every byte round-trips through [`decode`] exactly,
+/// it just doesn't shrink. A Huffman-coded writer can replace this one
+/// later without touching the ZLIB framing below.
+pub(crate) fn encode(input: &[u8]) -> Result<Vec<u8>, BlockDeserialiserError> {
+	// CMF: CINFO=7 (32K window), CM=8 (deflate). FLG chosen so that
+	// (CMF * 256 + FLG) % 31 == 0, with FDICT unset and FLEVEL=0.
+	let cmf: u8 = 0x78;
+	let mut flg: u8 = 0x01;
+	while !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+		flg += 1;
+	}
+
+	let mut out = Vec::with_capacity(input.len() + 6);
+	out.push(cmf);
+	out.push(flg);
+
+	const MAX_STORED_LEN: usize = u16::MAX as usize;
+	let mut chunks = input.chunks(MAX_STORED_LEN).peekable();
+	if chunks.peek().is_none() {
+		// An empty input is still one (final, empty) stored block.
+		write_stored_block(&mut out, &[], true);
+	}
+	while let Some(chunk) = chunks.next() {
+		write_stored_block(&mut out, chunk, chunks.peek().is_none());
+	}
+
+	out.extend_from_slice(&adler32(input).to_be_bytes());
+	Ok(out)
+}
+
+fn write_stored_block(out: &mut Vec<u8>, chunk: &[u8], is_final: bool) {
+	// BFINAL (1 bit) + BTYPE=00 (2 bits), padded to a byte boundary.
+	out.push(if is_final { 1 } else { 0 });
+	let len = chunk.len() as u16;
+	out.extend_from_slice(&len.to_le_bytes());
+	out.extend_from_slice(&(!len).to_le_bytes());
+	out.extend_from_slice(chunk);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::vec;
+
+	#[test]
+	fn decodes_stored_block() {
+		let zlib = vec![120, 1, 1, 3, 0, 252, 255, 65, 66, 67, 1, 141, 0, 199];
+		let decoded = decode(&zlib, 3).unwrap();
+		assert_eq!(decoded, b"ABC");
+	}
+
+	#[test]
+	fn decodes_fixed_huffman_block() {
+		let zlib = vec![
+			120, 1, 203, 72, 205, 201, 201, 87, 40, 207, 47, 202, 73, 209, 81, 40, 201, 200, 44, 86, 0,
+			162, 68, 133, 146, 212, 226, 18, 133, 252, 52, 133, 162, 212, 196, 28, 133, 148, 212, 180,
+			156, 196, 146, 84, 133, 228, 252, 220, 130, 162, 212, 226, 226, 204, 252, 60, 133, 242, 204,
+			146, 12, 160, 108, 65, 42, 80, 34, 5, 11, 163, 36, 181, 162, 4, 0, 28, 200, 34, 61,
+		];
+		let expected =
+			b"hello world, this is a test of real deflate compression with repeated repeated repeated text";
+		let decoded = decode(&zlib, expected.len()).unwrap();
+		assert_eq!(decoded, expected);
+	}
+
+	#[test]
+	fn decodes_dynamic_huffman_block() {
+		let zlib = vec![
+			120, 218, 109, 201, 209, 9, 192, 32, 16, 4, 209, 86, 182, 128, 52, 37, 113, 229, 132, 75, 78,
+			188, 5, 45, 63, 22, 16, 152, 143, 129, 103, 116, 15, 172, 152, 94, 47, 200, 122, 226, 84, 32,
+			166, 16, 13, 147, 197, 81, 217, 188, 136, 184, 227, 25, 147, 153, 61, 94, 172, 46, 59, 58,
+			120, 160, 254, 140, 184, 245, 1, 28, 200, 34, 61,
+		];
+		let expected =
+			b"hello world, this is a test of real deflate compression with repeated repeated repeated text";
+		let decoded = decode(&zlib, expected.len()).unwrap();
+		assert_eq!(decoded, expected);
+	}
+
+	#[test]
+	fn errors_on_truncated_stream() {
+		let zlib = vec![120, 1, 1, 3, 0, 252, 255, 65, 66];
+		assert!(decode(&zlib, 3).is_err());
+	}
+
+	#[test]
+	fn errors_on_corrupt_header() {
+		// CMF/FLG check byte deliberately broken (should be a multiple of 31).
+		let zlib = vec![120, 2, 1, 3, 0, 252, 255, 65, 66, 67, 1, 141, 0, 199];
+		assert!(decode(&zlib, 3).is_err());
+	}
+
+	#[test]
+	fn errors_on_adler32_mismatch() {
+		let mut zlib = vec![120, 1, 1, 3, 0, 252, 255, 65, 66, 67, 1, 141, 0, 199];
+		let last = zlib.len() - 1;
+		zlib[last] ^= 0xFF;
+		assert!(decode(&zlib, 3).is_err());
+	}
+
+	#[test]
+	fn encode_round_trips_through_decode() {
+		let input = b"hello world, this is a test of the stored-block encoder";
+		let encoded = encode(input).unwrap();
+		let decoded = decode(&encoded, input.len()).unwrap();
+		assert_eq!(decoded, input);
+	}
+}
@@ -0,0 +1,175 @@
+//! Top-level, incremental `.bgcode` file deserialiser.
+//!
+//! [`Deserialiser`] is a typed state machine (`State`) rather than a
+//! single function that reads everything in one pass. Each call to
+//! [`Deserialiser::deserialise`] advances through as much buffered input
+//! as it can via [`step`] and either returns a fully decoded block or
+//! tells the caller exactly how many more bytes it needs, so input can be
+//! fed in from a socket or file reader in arbitrarily small chunks rather
+//! than buffering the whole file up front.
+
+use alloc::vec::Vec;
+
+use crate::block::{BlockDeserialiser, BlockDeserialiserError, DeserialisedBlockData};
+use crate::file_header::FileChecksum;
+
+/// The 4-byte magic number every `.bgcode` file starts with, followed by
+/// a 4-byte version and a 2-byte checksum-kind field.
+const FILE_MAGIC: [u8; 4] = *b"GCDE";
+const FILE_HEADER_LEN: usize = 4 + 4 + 2;
+const BLOCK_HEADER_LEN: usize = 12;
+
+#[derive(Debug)]
+pub enum DeserialiserError {
+	Block(BlockDeserialiserError),
+	UnrecognisedMagic([u8; 4]),
+}
+
+impl From<BlockDeserialiserError> for DeserialiserError {
+	fn from(error: BlockDeserialiserError) -> Self {
+		Self::Block(error)
+	}
+}
+
+#[derive(Debug)]
+pub enum DeserialisedResult {
+	/// Not enough buffered input was available to make progress; the
+	/// caller should [`Deserialiser::digest`] at least this many more
+	/// bytes before calling [`Deserialiser::deserialise`] again.
+	MoreBytesRequired(usize),
+	/// A fully decoded block.
+	Block(DeserialisedBlockData),
+}
+
+#[derive(Debug)]
+enum State {
+	FileHeader,
+	BlockHeader,
+	BlockPayload(BlockDeserialiser),
+	Done,
+}
+
+fn clone_checksum(checksum: &FileChecksum) -> FileChecksum {
+	match checksum {
+		FileChecksum::None => FileChecksum::None,
+		FileChecksum::Crc32 => FileChecksum::Crc32,
+	}
+}
+
+/// Consume as much of `input` as the current state allows, returning the
+/// next state and, if a block (or a request for more bytes) is ready, the
+/// result to hand back to the caller. `input` is only advanced past bytes
+/// that were actually consumed; on a `MoreBytesRequired` result nothing is
+/// consumed, so the same bytes will be re-read once more have arrived.
+fn step(
+	state: State,
+	input: &mut &[u8],
+	checksum: &mut FileChecksum,
+	lenient: bool,
+) -> Result<(State, Option<DeserialisedResult>), DeserialiserError> {
+	match state {
+		State::FileHeader => {
+			if input.len() < FILE_HEADER_LEN {
+				let needed = FILE_HEADER_LEN - input.len();
+				return Ok((State::FileHeader, Some(DeserialisedResult::MoreBytesRequired(needed))));
+			}
+			let magic: [u8; 4] = input[0..4].try_into().unwrap();
+			if magic != FILE_MAGIC {
+				return Err(DeserialiserError::UnrecognisedMagic(magic));
+			}
+			let checksum_kind = u16::from_le_bytes(input[8..10].try_into().unwrap());
+			*checksum = FileChecksum::new(checksum_kind)?;
+			*input = &input[FILE_HEADER_LEN..];
+			Ok((State::BlockHeader, None))
+		}
+		State::BlockHeader => {
+			if input.len() < BLOCK_HEADER_LEN {
+				let needed = BLOCK_HEADER_LEN - input.len();
+				return Ok((State::BlockHeader, Some(DeserialisedResult::MoreBytesRequired(needed))));
+			}
+			let mut block = if lenient {
+				BlockDeserialiser::new_lenient(clone_checksum(checksum))
+			} else {
+				BlockDeserialiser::new(clone_checksum(checksum))
+			};
+			block.header_buf().copy_from_slice(&input[..BLOCK_HEADER_LEN]);
+			*input = &input[BLOCK_HEADER_LEN..];
+			Ok((State::BlockPayload(block), None))
+		}
+		State::BlockPayload(mut block) => {
+			let needed = block.block_size()?;
+			if input.len() < needed {
+				let missing = needed - input.len();
+				return Ok((
+					State::BlockPayload(block),
+					Some(DeserialisedResult::MoreBytesRequired(missing)),
+				));
+			}
+			block.data_buf()?.copy_from_slice(&input[..needed]);
+			*input = &input[needed..];
+			let decoded = block.deserialise()?;
+			Ok((State::BlockHeader, Some(DeserialisedResult::Block(decoded))))
+		}
+		State::Done => Ok((State::Done, Some(DeserialisedResult::MoreBytesRequired(0)))),
+	}
+}
+
+/// Incrementally deserialises a `.bgcode` file from chunks of bytes fed in
+/// via [`Deserialiser::digest`].
+#[derive(Debug)]
+pub struct Deserialiser {
+	state: State,
+	checksum: FileChecksum,
+	buf: Vec<u8>,
+	/// When `true`, each block's CRC-32 (if any) is parsed but not verified.
+	/// See [`BlockDeserialiser::new_lenient`].
+	lenient: bool,
+}
+
+impl Default for Deserialiser {
+	fn default() -> Self {
+		Self {
+			state: State::FileHeader,
+			checksum: FileChecksum::None,
+			buf: Vec::new(),
+			lenient: false,
+		}
+	}
+}
+
+impl Deserialiser {
+	/// Like [`Deserialiser::default`], but skips each block's CRC-32
+	/// verification even when the file declares [`FileChecksum::Crc32`].
+	pub fn new_lenient() -> Self {
+		Self {
+			lenient: true,
+			..Self::default()
+		}
+	}
+
+	/// Buffer more input bytes for [`Deserialiser::deserialise`] to consume.
+	pub fn digest(&mut self, input: &[u8]) {
+		self.buf.extend_from_slice(input);
+	}
+
+	/// Advance the state machine as far as the currently buffered input
+	/// allows, returning either the next decoded block or how many more
+	/// bytes are needed to make progress.
+	pub fn deserialise(&mut self) -> Result<DeserialisedResult, DeserialiserError> {
+		let mut cursor: &[u8] = &self.buf;
+		let state = core::mem::replace(&mut self.state, State::Done);
+
+		let (next_state, result) = step(state, &mut cursor, &mut self.checksum, self.lenient)?;
+		let consumed = self.buf.len() - cursor.len();
+		self.buf.drain(0..consumed);
+		self.state = next_state;
+
+		// `step` only returns `None` when it moved straight to a new state
+		// without producing a result (e.g. after the file header); keep
+		// stepping until there's something to report.
+		match result {
+			Some(result) => Ok(result),
+			None => self.deserialise(),
+		}
+	}
+}
@@ -0,0 +1,177 @@
+//! Heatshrink decoder (LZSS-style bitstream coder).
+//!
+//! The bgcode format uses two Heatshrink parameterisations, distinguished
+//! by window size `W` and lookahead size `L`: `Heatshrink11_4` (W=11, a
+//! 2048-byte window) and `Heatshrink12_4` (W=12, a 4096-byte window); both
+//! use L=4, giving a maximum match length of `2^L = 16` bytes. The
+//! bitstream is read MSB-first: each "tag" bit selects either a literal
+//! byte (tag 1, followed by 8 bits) or a backreference (tag 0, followed by
+//! a `W`-bit distance-minus-one and an `L`-bit length-minus-one).
+
+use alloc::vec::Vec;
+
+use crate::block::BlockDeserialiserError;
+
+struct BitReader<'a> {
+	data: &'a [u8],
+	byte_pos: usize,
+	bit_pos: u8,
+}
+
+impl<'a> BitReader<'a> {
+	fn new(data: &'a [u8]) -> Self {
+		Self {
+			data,
+			byte_pos: 0,
+			bit_pos: 0,
+		}
+	}
+
+	fn read_bit(&mut self) -> Result<u8, BlockDeserialiserError> {
+		let byte = *self
+			.data
+			.get(self.byte_pos)
+			.ok_or(BlockDeserialiserError::HeatshrinkDecodeError)?;
+		let bit = (byte >> (7 - self.bit_pos)) & 1;
+		self.bit_pos += 1;
+		if self.bit_pos == 8 {
+			self.bit_pos = 0;
+			self.byte_pos += 1;
+		}
+		Ok(bit)
+	}
+
+	fn read_bits(&mut self, count: u8) -> Result<usize, BlockDeserialiserError> {
+		let mut value: usize = 0;
+		for _ in 0..count {
+			value = (value << 1) | self.read_bit()? as usize;
+		}
+		Ok(value)
+	}
+}
+
+/// Packs MSB-first bits into bytes, padding the final byte with zeros.
+struct BitWriter {
+	bytes: Vec<u8>,
+	bit_pos: u8,
+}
+
+impl BitWriter {
+	fn new() -> Self {
+		Self {
+			bytes: Vec::new(),
+			bit_pos: 0,
+		}
+	}
+
+	fn push_bits(&mut self, value: usize, count: u8) {
+		for i in (0..count).rev() {
+			if self.bit_pos == 0 {
+				self.bytes.push(0);
+			}
+			let bit = ((value >> i) & 1) as u8;
+			let last = self.bytes.len() - 1;
+			self.bytes[last] |= bit << (7 - self.bit_pos);
+			self.bit_pos = (self.bit_pos + 1) % 8;
+		}
+	}
+}
+
+/// Decode a Heatshrink bitstream with window size `window_bits` and
+/// lookahead size `lookahead_bits`, stopping once `expected_len` bytes
+/// have been produced.
+pub(crate) fn decode(
+	input: &[u8],
+	window_bits: u8,
+	lookahead_bits: u8,
+	expected_len: usize,
+) -> Result<Vec<u8>, BlockDeserialiserError> {
+	let mut reader = BitReader::new(input);
+	let mut out: Vec<u8> = Vec::with_capacity(expected_len);
+
+	while out.len() < expected_len {
+		let tag = reader.read_bit()?;
+		if tag == 1 {
+			let literal = reader.read_bits(8)?;
+			out.push(literal as u8);
+		} else {
+			let index = reader.read_bits(window_bits)?;
+			let count = reader.read_bits(lookahead_bits)?;
+			let distance = index + 1;
+			let length = count + 1;
+			if distance > out.len() {
+				return Err(BlockDeserialiserError::HeatshrinkDecodeError);
+			}
+			// Same manual counter as `deflate::inflate_block`'s LZ77 copy,
+			// and for the same reason: `out` grows on every iteration and a
+			// back-reference may overlap itself (distance < length), so
+			// this can't be expressed as a range copy over a fixed slice.
+			let mut start = out.len() - distance;
+			#[allow(clippy::explicit_counter_loop)]
+			for _ in 0..length {
+				let byte = out[start];
+				out.push(byte);
+				start += 1;
+			}
+		}
+	}
+
+	if out.len() != expected_len {
+		return Err(BlockDeserialiserError::HeatshrinkDecodeError);
+	}
+	Ok(out)
+}
+
+/// Encode `input` as a Heatshrink bitstream.
+///
+/// This writer never emits backreferences: every byte is written as a
+/// literal (tag bit 1 followed by its 8 bits), so it round-trips through
+/// [`decode`] without needing a window/lookahead search. A writer that
+/// actually finds matches can replace this one later without touching the
+/// bit format below.
+pub(crate) fn encode(input: &[u8]) -> Result<Vec<u8>, BlockDeserialiserError> {
+	let mut writer = BitWriter::new();
+	for &byte in input {
+		writer.push_bits(1, 1);
+		writer.push_bits(byte as usize, 8);
+	}
+	Ok(writer.bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decodes_all_literal_stream() {
+		// "AB" encoded with no backreferences: tag=1, literal, tag=1, literal.
+		let mut w = BitWriter::new();
+		w.push_bits(1, 1);
+		w.push_bits(b'A' as usize, 8);
+		w.push_bits(1, 1);
+		w.push_bits(b'B' as usize, 8);
+		let decoded = decode(&w.bytes, 11, 4, 2).unwrap();
+		assert_eq!(decoded, b"AB");
+	}
+
+	#[test]
+	fn decodes_backreference() {
+		// "AAAA": literal 'A', then a backreference of distance 1, length 3.
+		let mut w = BitWriter::new();
+		w.push_bits(1, 1);
+		w.push_bits(b'A' as usize, 8);
+		w.push_bits(0, 1);
+		w.push_bits(0, 11); // distance - 1 = 0 => distance 1
+		w.push_bits(2, 4); // length - 1 = 2 => length 3
+		let decoded = decode(&w.bytes, 11, 4, 4).unwrap();
+		assert_eq!(decoded, b"AAAA");
+	}
+
+	#[test]
+	fn errors_on_truncated_stream() {
+		let mut w = BitWriter::new();
+		w.push_bits(1, 1);
+		w.push_bits(b'A' as usize, 8);
+		assert!(decode(&w.bytes, 11, 4, 4).is_err());
+	}
+}
@@ -0,0 +1,118 @@
+//! MeatPack decoder for the `GCode` block `encoding` field.
+//!
+//! MeatPack halves G-code size by packing the most common characters
+//! (digits, `.`, space, newline, and the axis/command letters `G` and `X`)
+//! two-to-a-byte as 4-bit lookup codes. A nibble value of `0xF` is an
+//! escape: the next full byte in the stream is a literal ASCII character
+//! rather than a pair of lookup codes. A leading `0xFF` byte is a command
+//! byte whose following byte toggles whether packing is currently active,
+//! which lets the encoder fall back to plain ASCII for runs of characters
+//! outside the lookup table (used by `MeatPackComment` for comment text).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::block::BlockDeserialiserError;
+
+const LOOKUP: [u8; 15] = [
+	b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'.', b' ', b'\n', b'G', b'X',
+];
+const ESCAPE_NIBBLE: u8 = 0xF;
+const COMMAND_BYTE: u8 = 0xFF;
+const COMMAND_ENABLE_PACKING: u8 = 0xFB;
+const COMMAND_DISABLE_PACKING: u8 = 0xFA;
+
+/// Decode a MeatPack (or MeatPackComment) bitstream back into plain ASCII
+/// G-code.
+pub(crate) fn decode(input: &[u8]) -> Result<String, BlockDeserialiserError> {
+	// Built up as raw bytes rather than `char`s: escaped literals and
+	// passthrough bytes carry arbitrary non-packable data (e.g. non-ASCII
+	// bytes in a comment) that `u8 as char` would reinterpret as a Unicode
+	// scalar and re-encode as multiple UTF-8 bytes, corrupting it.
+	let mut out = Vec::with_capacity(input.len() * 2);
+	let mut packing_enabled = true;
+	let mut bytes = input.iter().copied();
+
+	while let Some(byte) = bytes.next() {
+		if byte == COMMAND_BYTE {
+			match bytes.next() {
+				Some(COMMAND_ENABLE_PACKING) => packing_enabled = true,
+				Some(COMMAND_DISABLE_PACKING) => packing_enabled = false,
+				_ => return Err(BlockDeserialiserError::MeatPackDecodeError),
+			}
+			continue;
+		}
+
+		if !packing_enabled {
+			out.push(byte);
+			continue;
+		}
+
+		for nibble in [byte & 0x0F, byte >> 4] {
+			if nibble == ESCAPE_NIBBLE {
+				let literal = bytes
+					.next()
+					.ok_or(BlockDeserialiserError::MeatPackDecodeError)?;
+				out.push(literal);
+			} else {
+				out.push(
+					*LOOKUP
+						.get(nibble as usize)
+						.ok_or(BlockDeserialiserError::MeatPackDecodeError)?,
+				);
+			}
+		}
+	}
+
+	String::from_utf8(out).map_err(|_| BlockDeserialiserError::MeatPackDecodeError)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pack_nibbles(nibbles: &[u8]) -> alloc::vec::Vec<u8> {
+		nibbles
+			.chunks(2)
+			.map(|pair| {
+				let low = pair[0];
+				let high = pair.get(1).copied().unwrap_or(0);
+				low | (high << 4)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn decodes_packed_digits() {
+		// "G1" packed as two nibbles in one byte.
+		let packed = pack_nibbles(&[13, 1]);
+		assert_eq!(decode(&packed).unwrap(), "G1");
+	}
+
+	#[test]
+	fn decodes_escaped_literal() {
+		// Escape nibble followed by a literal 'Y' byte, then a packed '\n'.
+		let mut packed = pack_nibbles(&[0xF, 12]);
+		packed.insert(1, b'Y');
+		assert_eq!(decode(&packed).unwrap(), "Y\n");
+	}
+
+	#[test]
+	fn honours_disable_packing_command() {
+		let mut input = alloc::vec::Vec::new();
+		input.push(COMMAND_BYTE);
+		input.push(COMMAND_DISABLE_PACKING);
+		input.extend_from_slice(b";comment");
+		assert_eq!(decode(&input).unwrap(), ";comment");
+	}
+
+	#[test]
+	fn errors_on_non_utf8_escaped_literal() {
+		// An escaped literal byte >= 0x80 isn't valid UTF-8 on its own; a
+		// correct decoder must reject it rather than silently re-encoding
+		// it as a different, multi-byte sequence.
+		let mut packed = pack_nibbles(&[0xF, 1]);
+		packed.insert(1, 0xFF);
+		assert!(decode(&packed).is_err());
+	}
+}
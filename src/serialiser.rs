@@ -0,0 +1,110 @@
+//! Writers that mirror [`crate::block::BlockDeserialiser`]: given a block's
+//! kind, compression, parameters and payload, produce the exact byte
+//! layout `BlockDeserialiser` expects to read back.
+
+use alloc::vec::Vec;
+
+use crate::block::{BlockDeserialiserError, BlockKind, CompressionAlgorithm};
+use crate::codec;
+use crate::crc32;
+use crate::file_header::FileChecksum;
+
+/// Serialises a single block: a 12-byte header (8 bytes for an uncompressed
+/// block, which has no compressed-size field), the kind-specific
+/// parameter words, the (optionally compressed) payload, and an optional
+/// trailing CRC-32.
+#[derive(Debug)]
+pub struct BlockSerialiser {
+	kind: BlockKind,
+	compression: CompressionAlgorithm,
+	parameters: Vec<u16>,
+	payload: Vec<u8>,
+	checksum: FileChecksum,
+}
+
+impl BlockSerialiser {
+	pub fn new(
+		kind: BlockKind,
+		compression: CompressionAlgorithm,
+		parameters: Vec<u16>,
+		payload: Vec<u8>,
+		checksum: FileChecksum,
+	) -> Self {
+		Self {
+			kind,
+			compression,
+			parameters,
+			payload,
+			checksum,
+		}
+	}
+
+	fn encode_payload(&self) -> Result<Vec<u8>, BlockDeserialiserError> {
+		codec::codec_for(&self.compression).encode(&self.payload)
+	}
+
+	/// Serialise this block, returning its full on-disk bytes.
+	pub fn serialise(&self) -> Result<Vec<u8>, BlockDeserialiserError> {
+		let encoded_payload = self.encode_payload()?;
+
+		let mut out = Vec::new();
+		out.extend_from_slice(&self.kind.to_le_bytes());
+		out.extend_from_slice(&self.compression.to_le_bytes());
+		out.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+		if self.compression != CompressionAlgorithm::None {
+			out.extend_from_slice(&(encoded_payload.len() as u32).to_le_bytes());
+		}
+
+		for parameter in &self.parameters {
+			out.extend_from_slice(&parameter.to_le_bytes());
+		}
+		out.extend_from_slice(&encoded_payload);
+
+		if let FileChecksum::Crc32 = self.checksum {
+			let crc = crc32::checksum(&out);
+			out.extend_from_slice(&crc.to_le_bytes());
+		}
+
+		Ok(out)
+	}
+}
+
+/// The 4-byte magic number every `.bgcode` file starts with, mirroring
+/// [`crate::deserialiser`]'s `FILE_MAGIC`.
+const FILE_MAGIC: [u8; 4] = *b"GCDE";
+const FILE_VERSION: u32 = 1;
+
+/// Serialises a whole `.bgcode` file: the file header (magic, version,
+/// checksum kind), followed by every added block, in the order they were
+/// added.
+#[derive(Debug)]
+pub struct Serialiser {
+	checksum: FileChecksum,
+	blocks: Vec<BlockSerialiser>,
+}
+
+impl Serialiser {
+	pub fn new(checksum: FileChecksum) -> Self {
+		Self {
+			checksum,
+			blocks: Vec::new(),
+		}
+	}
+
+	pub fn add_block(&mut self, block: BlockSerialiser) -> &mut Self {
+		self.blocks.push(block);
+		self
+	}
+
+	/// Serialise the file header followed by every added block.
+	pub fn serialise(&self) -> Result<Vec<u8>, BlockDeserialiserError> {
+		let mut out = Vec::new();
+		out.extend_from_slice(&FILE_MAGIC);
+		out.extend_from_slice(&FILE_VERSION.to_le_bytes());
+		out.extend_from_slice(&self.checksum.to_le_bytes());
+		for block in &self.blocks {
+			out.extend_from_slice(&block.serialise()?);
+		}
+		Ok(out)
+	}
+}
@@ -16,5 +16,67 @@ fn deser_test_file() {
 	}
 }
 
-// #[test]
-// fn ser_test_file
+#[test]
+fn ser_test_file() {
+	use crate::block::{BlockDeserialiser, BlockKind, CompressionAlgorithm, DeserialisedBlockData};
+	use crate::file_header::FileChecksum;
+	use crate::serialiser::BlockSerialiser;
+	use alloc::vec;
+
+	let payload = b"; generated by a test\nG28\n".to_vec();
+	let serialised = BlockSerialiser::new(
+		BlockKind::PrintMetadata,
+		CompressionAlgorithm::None,
+		vec![0],
+		payload.clone(),
+		FileChecksum::Crc32,
+	)
+	.serialise()
+	.unwrap();
+
+	let mut deserialiser = BlockDeserialiser::new(FileChecksum::Crc32);
+	deserialiser
+		.header_buf()
+		.copy_from_slice(&serialised[0..12]);
+	deserialiser
+		.data_buf()
+		.unwrap()
+		.copy_from_slice(&serialised[12..]);
+
+	match deserialiser.deserialise().unwrap() {
+		DeserialisedBlockData::Ini(data) => assert_eq!(data, payload),
+		other => panic!("expected Ini data, got {other:?}"),
+	}
+}
+
+#[test]
+fn ser_deser_round_trip() {
+	use crate::block::{BlockKind, CompressionAlgorithm, DeserialisedBlockData};
+	use crate::file_header::FileChecksum;
+	use crate::serialiser::{BlockSerialiser, Serialiser};
+	use alloc::vec;
+
+	let payload = b"; generated by a test\nG28\n".to_vec();
+	let mut serialiser = Serialiser::new(FileChecksum::Crc32);
+	serialiser.add_block(BlockSerialiser::new(
+		BlockKind::PrintMetadata,
+		CompressionAlgorithm::None,
+		vec![0],
+		payload.clone(),
+		FileChecksum::Crc32,
+	));
+	let file_bytes = serialiser.serialise().unwrap();
+
+	let mut deserialiser = Deserialiser::default();
+	deserialiser.digest(&file_bytes);
+
+	loop {
+		match deserialiser.deserialise().unwrap() {
+			DeserialisedResult::MoreBytesRequired(_) => break,
+			DeserialisedResult::Block(DeserialisedBlockData::Ini(data)) => {
+				assert_eq!(data, payload);
+			}
+			other => panic!("expected Ini data, got {other:?}"),
+		}
+	}
+}